@@ -1,21 +1,43 @@
 /* copyright Remi Bernotavicius 2020 */
 
+use brotli::Decompressor;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use http_io::client::{HttpClient, StdTransport};
-use http_io::protocol::{HttpBody, OutgoingRequest};
+use http_io::protocol::{HttpBody, HttpStatus, OutgoingRequest};
 use http_io::url::Url;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::convert::Infallible;
 use std::fmt;
 use std::fs::File;
 use std::io;
+use std::io::Seek;
 use std::net::TcpStream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 
+/// Sent as the `User-Agent` on every outgoing request.
+const USER_AGENT: &str = concat!("wcp/", env!("CARGO_PKG_VERSION"));
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
 #[derive(Debug)]
 enum Error {
     Http(http_io::error::Error),
     Io(io::Error),
+    TooManyRedirects,
+    InvalidRedirect(String),
+    /// A `--recursive` tree (local or remote) nested deeper than `MAX_RECURSIVE_DEPTH`.
+    RecursionTooDeep,
+    /// The `--cache`d source replied `304 Not Modified`; the transfer should be skipped.
+    NotModified,
+    /// A segmented download's `Range` request resolved to something other than `206`.
+    UnexpectedStatus(HttpStatus),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -32,7 +54,17 @@ impl From<http_io::error::Error> for Error {
     }
 }
 
-#[derive(Debug)]
+impl Error {
+    /// Whether retrying the transfer is worth attempting.
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Io(_) | Self::Http(_) | Self::UnexpectedStatus(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 enum Location {
     Remote(Url),
     Local(PathBuf),
@@ -147,14 +179,197 @@ fn local_directory_location_is_not_dir() {
     assert!(!loc.is_dir());
 }
 
+/// The conditional-fetch validators recorded for a `--cache`d destination file.
+#[derive(Debug, Clone, Default)]
+struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    length: Option<u64>,
+}
+
+impl CacheEntry {
+    /// Loads the cache entry recorded for `path`'s `.cache` sidecar, if any.
+    fn load(path: &Path) -> Option<Self> {
+        let contents = std::fs::read_to_string(cache_sidecar_path(path)).ok()?;
+        let mut entry = Self::default();
+        for line in contents.lines() {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "etag" => entry.etag = Some(value.to_string()),
+                "last_modified" => entry.last_modified = Some(value.to_string()),
+                "length" => entry.length = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(entry)
+    }
+
+    /// Records this cache entry to `path`'s `.cache` sidecar.
+    fn save(&self, path: &Path) {
+        let mut contents = String::new();
+        if let Some(etag) = &self.etag {
+            contents.push_str(&format!("etag={}\n", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            contents.push_str(&format!("last_modified={}\n", last_modified));
+        }
+        if let Some(length) = self.length {
+            contents.push_str(&format!("length={}\n", length));
+        }
+        let _ = std::fs::write(cache_sidecar_path(path), contents);
+    }
+}
+
+/// Sidecar path for the conditional-fetch validators of a `--cache`d destination file.
+fn cache_sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".cache");
+    PathBuf::from(name)
+}
+
+/// Extra header/auth customization applied to every request a copy makes.
+#[derive(Clone, Default)]
+struct RequestConfig {
+    headers: Vec<(String, String)>,
+    /// The rendered `Authorization` header value, e.g. `Basic <base64>`.
+    authorization: Option<String>,
+}
+
+impl RequestConfig {
+    fn new(options: &Options) -> Self {
+        let authorization = options.user.as_ref().map(|(user, password)| {
+            format!("Basic {}", base64::encode(format!("{}:{}", user, password)))
+        });
+        Self {
+            headers: options.headers.clone(),
+            authorization,
+        }
+    }
+
+    fn apply(&self, request: &mut OutgoingRequest<&mut StdTransport>) {
+        for (name, value) in &self.headers {
+            request.set_header(name.clone(), value.clone());
+        }
+        if let Some(authorization) = &self.authorization {
+            request.set_header("Authorization", authorization.clone());
+        }
+    }
+}
+
+/// Newtype so `StreamConnector` can be implemented for it without hitting the orphan rule.
+struct TlsStream(native_tls::TlsStream<TcpStream>);
+
+impl io::Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::Read::read(&mut self.0, buf)
+    }
+}
+
+impl io::Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(&mut self.0, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.0)
+    }
+}
+
+impl http_io::client::StreamConnector for TlsStream {
+    fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let tcp = TcpStream::connect((host, port))?;
+        let connector =
+            native_tls::TlsConnector::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        connector
+            .connect(host, tcp)
+            .map(TlsStream)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+/// Whether `url` uses the `https` scheme.
+fn is_https(url: &Url) -> bool {
+    url.to_string().starts_with("https://")
+}
+
+/// An HTTP client capable of both plain and TLS-wrapped connections.
+struct Transport {
+    plain: HttpClient<TcpStream>,
+    tls: HttpClient<TlsStream>,
+}
+
+impl Transport {
+    fn new() -> Self {
+        Self {
+            plain: HttpClient::<TcpStream>::new(),
+            tls: HttpClient::<TlsStream>::new(),
+        }
+    }
+
+    fn get(&mut self, url: Url) -> Result<OutgoingRequest<&mut StdTransport>> {
+        Ok(if is_https(&url) {
+            self.tls.get(url)?
+        } else {
+            self.plain.get(url)?
+        })
+    }
+
+    fn put(&mut self, url: Url) -> Result<OutgoingRequest<&mut StdTransport>> {
+        Ok(if is_https(&url) {
+            self.tls.put(url)?
+        } else {
+            self.plain.put(url)?
+        })
+    }
+}
+
+/// Settings for a single copy, independent of which `CopySource`/`CopySink` it uses.
+struct CopyConfig {
+    max_redirects: u32,
+    resume_offset: u64,
+    resume_validator: Option<String>,
+    compress: bool,
+    connections: u32,
+    cache: bool,
+    cache_validator: Option<CacheEntry>,
+    request_config: RequestConfig,
+    /// Set so a resume validator can be staged to its sidecar as soon as it's known.
+    local_destination_path: Option<PathBuf>,
+}
+
 struct CopyContext {
-    http_client: HttpClient<TcpStream>,
+    http_client: Transport,
+    max_redirects: u32,
+    resume_offset: u64,
+    resume_validator: Option<String>,
+    /// Set by `open_for_read` once it knows whether the transfer picked up at `resume_offset`.
+    resumed: bool,
+    /// Validator seen on this transfer, to record for a future resume attempt.
+    resume_validator_received: Option<String>,
+    compress: bool,
+    cache: bool,
+    cache_validator: Option<CacheEntry>,
+    /// Validators to record in the `--cache` sidecar for a future conditional fetch.
+    cache_entry_received: Option<CacheEntry>,
+    request_config: RequestConfig,
+    local_destination_path: Option<PathBuf>,
 }
 
 impl CopyContext {
-    fn new() -> Self {
+    fn new(config: &CopyConfig) -> Self {
         Self {
-            http_client: HttpClient::<TcpStream>::new(),
+            http_client: Transport::new(),
+            max_redirects: config.max_redirects,
+            resume_offset: config.resume_offset,
+            resume_validator: config.resume_validator.clone(),
+            resumed: false,
+            resume_validator_received: None,
+            compress: config.compress,
+            cache: config.cache,
+            cache_validator: config.cache_validator.clone(),
+            cache_entry_received: None,
+            request_config: config.request_config.clone(),
+            local_destination_path: config.local_destination_path.clone(),
         }
     }
 }
@@ -174,7 +389,7 @@ trait CopySource<'a> {
 
 trait CopySink<'a> {
     type Stream: io::Write + StreamFinish + 'a;
-    fn open_for_write(&self, context: &'a mut CopyContext) -> Result<Self::Stream>;
+    fn open_for_write(&self, context: &'a mut CopyContext, resume: bool) -> Result<Self::Stream>;
 }
 
 impl<R: io::Read> StreamSize for HttpBody<R> {
@@ -190,18 +405,431 @@ impl<S: io::Read + io::Write> StreamFinish for OutgoingRequest<S> {
     }
 }
 
+fn is_redirect(status: HttpStatus) -> bool {
+    matches!(
+        status,
+        HttpStatus::MovedPermanently
+            | HttpStatus::Found
+            | HttpStatus::SeeOther
+            | HttpStatus::TemporaryRedirect
+            | HttpStatus::PermanentRedirect
+    )
+}
+
+/// Resolves a `Location` header (absolute, absolute-path, or relative) against `base`.
+fn resolve_redirect(base: &Url, location: &str) -> Result<Url> {
+    if location.contains("://") {
+        location
+            .parse()
+            .map_err(|_| Error::InvalidRedirect(location.into()))
+    } else if location.starts_with('/') {
+        let mut next = base.clone();
+        next.path = location
+            .parse()
+            .map_err(|_| Error::InvalidRedirect(location.into()))?;
+        Ok(next)
+    } else {
+        let mut directory: Vec<&str> = base.path.components().collect();
+        if !base.path.trailing_slash() {
+            directory.pop();
+        }
+        let resolved = format!("/{}/{}", directory.join("/"), location).replace("//", "/");
+        let mut next = base.clone();
+        next.path = resolved
+            .parse()
+            .map_err(|_| Error::InvalidRedirect(location.into()))?;
+        Ok(next)
+    }
+}
+
+#[test]
+fn resolve_redirect_absolute() {
+    let base: Url = "http://ex.com/a/b".parse().unwrap();
+    let resolved = resolve_redirect(&base, "http://other.com/c").unwrap();
+    assert_eq!(resolved.to_string(), "http://other.com/c");
+}
+
+#[test]
+fn resolve_redirect_absolute_path() {
+    let base: Url = "http://ex.com/a/b".parse().unwrap();
+    let resolved = resolve_redirect(&base, "/c/d").unwrap();
+    assert_eq!(resolved.to_string(), "http://ex.com/c/d");
+}
+
+#[test]
+fn resolve_redirect_relative() {
+    let base: Url = "http://ex.com/a/b/page".parse().unwrap();
+    let resolved = resolve_redirect(&base, "next.html").unwrap();
+    assert_eq!(resolved.to_string(), "http://ex.com/a/b/next.html");
+}
+
+#[test]
+fn resolve_redirect_relative_from_directory() {
+    let base: Url = "http://ex.com/a/b/".parse().unwrap();
+    let resolved = resolve_redirect(&base, "next.html").unwrap();
+    assert_eq!(resolved.to_string(), "http://ex.com/a/b/next.html");
+}
+
+#[test]
+fn resolve_redirect_relative_from_root() {
+    let base: Url = "http://ex.com/page".parse().unwrap();
+    let resolved = resolve_redirect(&base, "next.html").unwrap();
+    assert_eq!(resolved.to_string(), "http://ex.com/next.html");
+}
+
+/// A possibly-decompressed response body.
+enum DecodedBody<R: io::Read> {
+    Plain(HttpBody<R>),
+    Gzip(GzDecoder<HttpBody<R>>),
+    Brotli(Decompressor<HttpBody<R>>),
+}
+
+impl<R: io::Read> io::Read for DecodedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(body) => body.read(buf),
+            Self::Gzip(body) => body.read(buf),
+            Self::Brotli(body) => body.read(buf),
+        }
+    }
+}
+
+impl<R: io::Read> StreamSize for DecodedBody<R> {
+    fn stream_size(&self) -> Option<u64> {
+        match self {
+            Self::Plain(body) => body.content_length(),
+            Self::Gzip(_) | Self::Brotli(_) => None,
+        }
+    }
+}
+
 impl<'a> CopySource<'a> for Url {
-    type Stream = HttpBody<&'a mut StdTransport>;
+    type Stream = DecodedBody<&'a mut StdTransport>;
     fn open_for_read(&self, context: &'a mut CopyContext) -> Result<Self::Stream> {
-        Ok(context.http_client.get(self.clone())?.finish()?.body)
+        let mut current = self.clone();
+        for _ in 0..=context.max_redirects {
+            let mut request = context.http_client.get(current.clone())?;
+            request.set_header("User-Agent", USER_AGENT);
+            if context.resume_offset > 0 {
+                // A compressed response can't be resumed byte-for-byte: the
+                // partial body we already have on disk is decoded plaintext,
+                // but a `Range` request against a compressed representation
+                // would start mid gzip/br stream and fail to decode. Leave
+                // `Accept-Encoding` off so the server gives us an
+                // uncompressed (and therefore range-able) body instead.
+                request.set_header("Range", format!("bytes={}-", context.resume_offset));
+                if let Some(validator) = &context.resume_validator {
+                    request.set_header("If-Range", validator.clone());
+                }
+            } else {
+                request.set_header("Accept-Encoding", "gzip, br");
+            }
+            if let Some(cache) = &context.cache_validator {
+                if let Some(etag) = &cache.etag {
+                    request.set_header("If-None-Match", etag.clone());
+                }
+                if let Some(last_modified) = &cache.last_modified {
+                    request.set_header("If-Modified-Since", last_modified.clone());
+                }
+            }
+            context.request_config.apply(&mut request);
+            let response = request.finish()?;
+            if !is_redirect(response.status) {
+                if response.status == HttpStatus::NotModified {
+                    return Err(Error::NotModified);
+                }
+                context.resumed =
+                    context.resume_offset > 0 && response.status == HttpStatus::PartialContent;
+                context.resume_validator_received = response
+                    .headers
+                    .get("ETag")
+                    .or_else(|| response.headers.get("Last-Modified"))
+                    .map(|s| s.to_string());
+                // Stage the validator immediately, rather than waiting for the
+                // whole copy to finish: if the body copy below fails partway
+                // through, a retry within this same invocation still needs
+                // `If-Range` set to the validator of the attempt that staged
+                // the bytes currently on disk.
+                if let Some(path) = &context.local_destination_path {
+                    update_resume_sidecar(path, context.resume_validator_received.as_deref());
+                }
+                if context.cache {
+                    context.cache_entry_received = Some(CacheEntry {
+                        etag: response.headers.get("ETag").map(|s| s.to_string()),
+                        last_modified: response.headers.get("Last-Modified").map(|s| s.to_string()),
+                        length: response
+                            .headers
+                            .get("Content-Length")
+                            .and_then(|s| s.parse().ok()),
+                    });
+                }
+                return Ok(match response.headers.get("Content-Encoding") {
+                    Some("gzip") => DecodedBody::Gzip(GzDecoder::new(response.body)),
+                    Some("br") => DecodedBody::Brotli(Decompressor::new(response.body, 4096)),
+                    _ => DecodedBody::Plain(response.body),
+                });
+            }
+            let location = response
+                .headers
+                .get("Location")
+                .ok_or_else(|| Error::InvalidRedirect("missing Location header".into()))?;
+            current = resolve_redirect(&current, location)?;
+        }
+        Err(Error::TooManyRedirects)
+    }
+}
+
+/// A possibly gzip-compressed upload body.
+enum EncodedSink<'a> {
+    Plain(OutgoingRequest<&'a mut StdTransport>),
+    Gzip(GzEncoder<OutgoingRequest<&'a mut StdTransport>>),
+}
+
+impl<'a> io::Write for EncodedSink<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(sink) => sink.write(buf),
+            Self::Gzip(sink) => sink.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(sink) => sink.flush(),
+            Self::Gzip(sink) => sink.flush(),
+        }
+    }
+}
+
+impl<'a> StreamFinish for EncodedSink<'a> {
+    fn stream_finish(self) -> Result<()> {
+        match self {
+            Self::Plain(sink) => sink.stream_finish(),
+            Self::Gzip(sink) => sink.finish()?.stream_finish(),
+        }
     }
 }
 
 impl<'a> CopySink<'a> for Url {
-    type Stream = OutgoingRequest<&'a mut StdTransport>;
-    fn open_for_write(&self, context: &'a mut CopyContext) -> Result<Self::Stream> {
-        Ok(context.http_client.put(self.clone())?)
+    type Stream = EncodedSink<'a>;
+    fn open_for_write(&self, context: &'a mut CopyContext, _resume: bool) -> Result<Self::Stream> {
+        let mut request = context.http_client.put(self.clone())?;
+        request.set_header("User-Agent", USER_AGENT);
+        context.request_config.apply(&mut request);
+        if context.compress {
+            request.set_header("Content-Encoding", "gzip");
+            Ok(EncodedSink::Gzip(GzEncoder::new(
+                request,
+                Compression::default(),
+            )))
+        } else {
+            Ok(EncodedSink::Plain(request))
+        }
+    }
+}
+
+/// Minimum size a remote resource must report before segmenting its download.
+const SEGMENTED_DOWNLOAD_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Parses the total resource size out of a `Content-Range: bytes 0-0/12345` header value.
+fn parse_content_range_total(header: Option<&str>) -> Option<u64> {
+    header?.rsplit('/').next()?.parse().ok()
+}
+
+/// Probes whether `url` honors byte-range requests and, if so, the total resource size.
+fn probe_range_support(
+    http_client: &mut Transport,
+    url: &Url,
+    max_redirects: u32,
+    request_config: &RequestConfig,
+) -> Result<Option<(Url, u64)>> {
+    let mut current = url.clone();
+    for _ in 0..=max_redirects {
+        let mut request = http_client.get(current.clone())?;
+        request.set_header("User-Agent", USER_AGENT);
+        request.set_header("Range", "bytes=0-0");
+        request_config.apply(&mut request);
+        let response = request.finish()?;
+        if !is_redirect(response.status) {
+            if response.status != HttpStatus::PartialContent {
+                return Ok(None);
+            }
+            return Ok(
+                parse_content_range_total(response.headers.get("Content-Range"))
+                    .map(|total_len| (current, total_len)),
+            );
+        }
+        let location = response
+            .headers
+            .get("Location")
+            .ok_or_else(|| Error::InvalidRedirect("missing Location header".into()))?;
+        current = resolve_redirect(&current, location)?;
     }
+    Err(Error::TooManyRedirects)
+}
+
+/// Clamps `--connections` so `segment_ranges` never divides by more than `total_len`.
+fn clamp_connections(connections: u32, total_len: u64) -> u32 {
+    (u64::from(connections).min(total_len).max(1)) as u32
+}
+
+/// Splits `[0, total_len)` into `connections` contiguous, inclusive byte ranges.
+fn segment_ranges(total_len: u64, connections: u32) -> Vec<(u64, u64)> {
+    let connections = u64::from(connections);
+    let segment_len = total_len / connections;
+    (0..connections)
+        .map(|i| {
+            let start = i * segment_len;
+            let end = if i == connections - 1 {
+                total_len - 1
+            } else {
+                start + segment_len - 1
+            };
+            (start, end)
+        })
+        .collect()
+}
+
+/// Downloads one byte range of a segmented transfer into its slice of the
+/// pre-allocated destination file, rejecting anything other than `206 Partial Content`.
+fn download_segment(
+    url: Url,
+    tmp_path: PathBuf,
+    start: u64,
+    end: u64,
+    max_redirects: u32,
+    request_config: RequestConfig,
+    transferred: Arc<AtomicU64>,
+    progress: ProgressBar,
+) -> Result<()> {
+    let mut http_client = Transport::new();
+    let mut current = url;
+    let mut body = None;
+    for _ in 0..=max_redirects {
+        let mut request = http_client.get(current.clone())?;
+        request.set_header("User-Agent", USER_AGENT);
+        request.set_header("Range", format!("bytes={}-{}", start, end));
+        request_config.apply(&mut request);
+        let response = request.finish()?;
+        if !is_redirect(response.status) {
+            if response.status != HttpStatus::PartialContent {
+                return Err(Error::UnexpectedStatus(response.status));
+            }
+            body = Some(response.body);
+            break;
+        }
+        let location = response
+            .headers
+            .get("Location")
+            .ok_or_else(|| Error::InvalidRedirect("missing Location header".into()))?;
+        current = resolve_redirect(&current, location)?;
+    }
+    let mut body = body.ok_or(Error::TooManyRedirects)?;
+
+    let mut file = std::fs::OpenOptions::new().write(true).open(&tmp_path)?;
+    file.seek(io::SeekFrom::Start(start))?;
+
+    let mut buf = [0u8; DEFAULT_BUF_SIZE];
+    loop {
+        let len = io::Read::read(&mut body, &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        io::Write::write_all(&mut file, &buf[..len])?;
+        transferred.fetch_add(len as u64, Ordering::Relaxed);
+        progress.set_position(transferred.load(Ordering::Relaxed));
+    }
+    Ok(())
+}
+
+/// Path of a segmented download's temporary file, kept distinct from `tmp_path_for`
+/// since it's pre-sized to its final length and so can't be inspected by `resume_state`.
+fn segmented_tmp_path_for(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".segtmp");
+    PathBuf::from(name)
+}
+
+/// Attempts a segmented, multi-connection download, returning `Ok(None)` when the
+/// caller should fall back to the single-stream `do_io_copy` path.
+fn try_segmented_download(
+    config: &CopyConfig,
+    source: &Url,
+    destination: &Path,
+) -> Result<Option<TransferOutcome>> {
+    if config.connections <= 1 || config.resume_offset > 0 || config.cache {
+        return Ok(None);
+    }
+
+    let mut http_client = Transport::new();
+    let (source, total_len) = match probe_range_support(
+        &mut http_client,
+        source,
+        config.max_redirects,
+        &config.request_config,
+    )? {
+        Some((source, total_len)) if total_len >= SEGMENTED_DOWNLOAD_THRESHOLD => {
+            (source, total_len)
+        }
+        _ => return Ok(None),
+    };
+
+    let tmp_path = segmented_tmp_path_for(destination);
+    File::create(&tmp_path)?.set_len(total_len)?;
+
+    let progress = ProgressBar::new(total_len);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("{wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}) (eta {eta})"),
+    );
+    let transferred = Arc::new(AtomicU64::new(0));
+
+    let connections = clamp_connections(config.connections, total_len);
+
+    let handles: Vec<_> = segment_ranges(total_len, connections)
+        .into_iter()
+        .map(|(start, end)| {
+            let source = source.clone();
+            let tmp_path = tmp_path.clone();
+            let max_redirects = config.max_redirects;
+            let request_config = config.request_config.clone();
+            let transferred = transferred.clone();
+            let progress = progress.clone();
+            std::thread::spawn(move || {
+                download_segment(
+                    source,
+                    tmp_path,
+                    start,
+                    end,
+                    max_redirects,
+                    request_config,
+                    transferred,
+                    progress,
+                )
+            })
+        })
+        .collect();
+
+    // Join every thread before acting on the first error, rather than
+    // bailing out with `?` on the first `Err`: other segments may still be
+    // writing into `tmp_path` concurrently, and removing it out from under
+    // them would be a race.
+    let results: Vec<Result<()>> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("segment thread panicked"))
+        .collect();
+    if let Some(err) = results.into_iter().find_map(|r| r.err()) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&tmp_path)?
+        .sync_all()?;
+    std::fs::rename(&tmp_path, destination)?;
+
+    Ok(Some(TransferOutcome::default()))
 }
 
 impl StreamSize for File {
@@ -219,15 +847,62 @@ impl StreamFinish for File {
 
 impl<'a> CopySource<'a> for PathBuf {
     type Stream = File;
-    fn open_for_read(&self, _context: &'a mut CopyContext) -> Result<Self::Stream> {
-        Ok(File::open(self)?)
+    fn open_for_read(&self, context: &'a mut CopyContext) -> Result<Self::Stream> {
+        let mut file = File::open(self)?;
+        if context.resume_offset > 0 {
+            file.seek(io::SeekFrom::Start(context.resume_offset))?;
+            context.resumed = true;
+        }
+        Ok(file)
+    }
+}
+
+/// Path of the temporary file a local download is staged into before being renamed.
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Writes to a sibling `.tmp` file; `stream_finish` fsyncs and renames it into place.
+struct LocalSink {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: File,
+}
+
+impl io::Write for LocalSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(&mut self.file, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.file)
+    }
+}
+
+impl StreamFinish for LocalSink {
+    fn stream_finish(self) -> Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        Ok(())
     }
 }
 
 impl<'a> CopySink<'a> for PathBuf {
-    type Stream = File;
-    fn open_for_write(&self, _context: &'a mut CopyContext) -> Result<Self::Stream> {
-        Ok(File::create(self)?)
+    type Stream = LocalSink;
+    fn open_for_write(&self, _context: &'a mut CopyContext, resume: bool) -> Result<Self::Stream> {
+        let tmp_path = tmp_path_for(self);
+        let file = if resume {
+            std::fs::OpenOptions::new().append(true).open(&tmp_path)?
+        } else {
+            File::create(&tmp_path)?
+        };
+        Ok(LocalSink {
+            path: self.clone(),
+            tmp_path,
+            file,
+        })
     }
 }
 
@@ -236,6 +911,58 @@ impl<'a> CopySink<'a> for PathBuf {
 struct Options {
     source: Location,
     destination: Location,
+    /// Maximum number of HTTP redirects to follow before giving up.
+    #[structopt(long, default_value = "10")]
+    max_redirects: u32,
+    /// Resume a partially downloaded destination file using an HTTP Range
+    /// request, rather than starting over from the beginning.
+    #[structopt(long = "continue")]
+    resume: bool,
+    /// Number of times to retry a transfer after a connection/IO error,
+    /// with an exponentially increasing delay between attempts.
+    #[structopt(long, default_value = "5")]
+    retries: u32,
+    /// Gzip-compress the request body when uploading to a remote
+    /// destination.
+    #[structopt(long)]
+    compress: bool,
+    /// Number of connections to use for a segmented parallel download, when
+    /// the remote server supports byte ranges and the file is large enough
+    /// to benefit.
+    #[structopt(long, default_value = "1")]
+    connections: u32,
+    /// Recursively copy a directory tree, mirroring every entry under
+    /// `source` to the matching path under `destination`.
+    #[structopt(long)]
+    recursive: bool,
+    /// Skip re-downloading a destination file whose remote content is
+    /// unchanged, using a `.cache` sidecar of `ETag`/`Last-Modified`
+    /// validators recorded from the prior run.
+    #[structopt(long)]
+    cache: bool,
+    /// Extra header to send with every request, as `NAME: VALUE`. May be
+    /// given multiple times.
+    #[structopt(long = "header", parse(try_from_str = parse_header_arg))]
+    headers: Vec<(String, String)>,
+    /// Send an `Authorization: Basic` header built from `USER:PASSWORD`.
+    #[structopt(long, parse(try_from_str = parse_user_arg))]
+    user: Option<(String, String)>,
+}
+
+/// Parses a `--header NAME: VALUE` argument into a name/value pair.
+fn parse_header_arg(arg: &str) -> std::result::Result<(String, String), String> {
+    let (name, value) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("invalid header {:?}, expected NAME: VALUE", arg))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+/// Parses a `--user USER:PASSWORD` argument into a username/password pair.
+fn parse_user_arg(arg: &str) -> std::result::Result<(String, String), String> {
+    let (user, password) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("invalid user {:?}, expected USER:PASSWORD", arg))?;
+    Ok((user.to_string(), password.to_string()))
 }
 
 const DEFAULT_BUF_SIZE: usize = 8 * 1024;
@@ -263,19 +990,49 @@ where
     }
 }
 
-fn do_io_copy<SOURCE, SINK>(source: SOURCE, destination: SINK) -> Result<()>
+/// What a single copy attempt accomplished, surfaced back to `do_copy` so it
+/// can update sidecars and report progress appropriately.
+#[derive(Default)]
+struct TransferOutcome {
+    /// The `ETag`/`Last-Modified` the response carried. `open_for_read` has
+    /// already staged it in the `.resume` sidecar; `do_copy` does not record
+    /// it again and clears that sidecar once the transfer completes.
+    resume_validator: Option<String>,
+    /// The validators to record in the `--cache` sidecar for a future
+    /// conditional fetch, when `--cache` was requested.
+    cache_entry: Option<CacheEntry>,
+    /// Set when a `--cache`d source reported its content was unchanged, so
+    /// the transfer was skipped entirely.
+    skipped: bool,
+}
+
+fn do_io_copy<SOURCE, SINK>(
+    config: &CopyConfig,
+    source: SOURCE,
+    destination: SINK,
+) -> Result<TransferOutcome>
 where
     for<'a> SOURCE: CopySource<'a>,
     for<'a> SINK: CopySink<'a>,
 {
-    let mut source_context = CopyContext::new();
-    let mut destination_context = CopyContext::new();
-
-    let mut source_stream = source.open_for_read(&mut source_context)?;
-    let mut destination_stream = destination.open_for_write(&mut destination_context)?;
+    let mut source_context = CopyContext::new(config);
+    let mut destination_context = CopyContext::new(config);
+
+    let mut source_stream = match source.open_for_read(&mut source_context) {
+        Ok(stream) => stream,
+        Err(Error::NotModified) => {
+            return Ok(TransferOutcome {
+                skipped: true,
+                ..Default::default()
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    let resumed = source_context.resumed;
+    let mut destination_stream = destination.open_for_write(&mut destination_context, resumed)?;
 
     let mut progress = match source_stream.stream_size() {
-        Some(length) => ProgressBar::new(length),
+        Some(length) => ProgressBar::new(length + if resumed { config.resume_offset } else { 0 }),
         None => ProgressBar::new_spinner(),
     };
 
@@ -284,67 +1041,403 @@ where
             .template("{wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}) (eta {eta})"),
     );
 
+    if resumed {
+        progress.inc(config.resume_offset);
+    }
+
     io_copy_with_progress(&mut source_stream, &mut destination_stream, &mut progress)?;
 
     destination_stream.stream_finish()?;
 
-    Ok(())
+    Ok(TransferOutcome {
+        resume_validator: source_context.resume_validator_received,
+        cache_entry: source_context.cache_entry_received,
+        skipped: false,
+    })
 }
 
-fn do_copy(source: Location, mut destination: Location) -> Result<()> {
-    if destination.is_dir() {
-        destination.push(&source.name());
+/// Sidecar path for the resume validator of a partially downloaded destination file.
+fn resume_sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".resume");
+    PathBuf::from(name)
+}
+
+/// Determines where a transfer should resume from, from the staged `.tmp` file and
+/// its recorded validator.
+fn resume_state(destination: &Location) -> (u64, Option<String>) {
+    let path = match destination {
+        Location::Local(path) => path,
+        Location::Remote(_) => return (0, None),
+    };
+    match std::fs::metadata(tmp_path_for(path)) {
+        Ok(metadata) if metadata.len() > 0 => {
+            let validator = std::fs::read_to_string(resume_sidecar_path(path)).ok();
+            (metadata.len(), validator)
+        }
+        _ => (0, None),
+    }
+}
+
+/// Loads the `--cache` sidecar validators recorded for `destination`.
+fn cache_state(destination: &Location, enabled: bool) -> Option<CacheEntry> {
+    if !enabled {
+        return None;
     }
+    match destination {
+        Location::Local(path) => CacheEntry::load(path),
+        Location::Remote(_) => None,
+    }
+}
 
-    println!("copying {} to {}", source, destination);
+/// Records (or clears) the resume validator for a destination file.
+fn update_resume_sidecar(path: &Path, validator: Option<&str>) {
+    let sidecar = resume_sidecar_path(path);
+    match validator {
+        Some(validator) => {
+            let _ = std::fs::write(sidecar, validator);
+        }
+        None => {
+            let _ = std::fs::remove_file(sidecar);
+        }
+    }
+}
 
+fn do_copy_once(
+    config: &CopyConfig,
+    source: Location,
+    destination: Location,
+) -> Result<TransferOutcome> {
     match (source, destination) {
-        (Location::Local(source), Location::Local(destination)) => do_io_copy(source, destination),
-        (Location::Local(source), Location::Remote(destination)) => do_io_copy(source, destination),
-        (Location::Remote(source), Location::Local(destination)) => do_io_copy(source, destination),
+        (Location::Local(source), Location::Local(destination)) => {
+            do_io_copy(config, source, destination)
+        }
+        (Location::Local(source), Location::Remote(destination)) => {
+            do_io_copy(config, source, destination)
+        }
+        (Location::Remote(source), Location::Local(destination)) => {
+            match try_segmented_download(config, &source, &destination)? {
+                Some(outcome) => Ok(outcome),
+                None => do_io_copy(config, source, destination),
+            }
+        }
         (Location::Remote(source), Location::Remote(destination)) => {
-            do_io_copy(source, destination)
+            do_io_copy(config, source, destination)
         }
     }
 }
 
-#[cfg(test)]
-use http_io::{
-    protocol::{HttpResponse, HttpStatus},
-    server::{HttpRequestHandler, HttpServer},
-};
+fn do_copy(options: &Options, source: Location, mut destination: Location) -> Result<()> {
+    if destination.is_dir() {
+        destination.push(&source.name());
+    }
 
-#[cfg(test)]
-struct TestDownloadHandler(String);
+    println!("copying {} to {}", source, destination);
 
-#[cfg(test)]
-impl<I: io::Read> HttpRequestHandler<I> for TestDownloadHandler {
-    type Error = http_io::error::Error;
+    let local_destination_path = match &destination {
+        Location::Local(path) => Some(path.clone()),
+        Location::Remote(_) => None,
+    };
 
-    fn get(&mut self, _uri: String) -> http_io::error::Result<HttpResponse<Box<dyn io::Read>>> {
-        Ok(HttpResponse::from_string(HttpStatus::OK, &self.0))
+    // A plain (non-`--continue`d) run should never pick up stale progress
+    // left behind by an unrelated earlier invocation.
+    if !options.resume {
+        if let Some(path) = &local_destination_path {
+            let _ = std::fs::remove_file(tmp_path_for(path));
+            let _ = std::fs::remove_file(resume_sidecar_path(path));
+            let _ = std::fs::remove_file(segmented_tmp_path_for(path));
+        }
     }
-}
 
-/// End-to-end integration test of downloading a file from an HTTP server.
-#[test]
-fn test_download() {
-    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
-    let server_address = server_socket.local_addr().unwrap();
-    let handler = TestDownloadHandler("file_data".into());
-    let mut server = HttpServer::new(server_socket, handler);
-    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+    let mut delay = INITIAL_RETRY_DELAY;
+    let mut attempt = 0;
+    let result = loop {
+        let (resume_offset, resume_validator) = resume_state(&destination);
+        let cache_validator = cache_state(&destination, options.cache);
+        let config = CopyConfig {
+            max_redirects: options.max_redirects,
+            resume_offset,
+            resume_validator,
+            compress: options.compress,
+            connections: options.connections,
+            cache: options.cache,
+            cache_validator,
+            request_config: RequestConfig::new(options),
+            local_destination_path: local_destination_path.clone(),
+        };
 
-    let url = format!("http://localhost:{}/", server_address.port())
-        .parse()
-        .unwrap();
-    let temporary_file = tempfile::NamedTempFile::new().unwrap();
-    let local_path = temporary_file.path().to_path_buf();
+        match do_copy_once(&config, source.clone(), destination.clone()) {
+            Ok(outcome) => break Ok(outcome),
+            Err(e) if attempt < options.retries && e.is_retryable() => {
+                attempt += 1;
+                eprintln!(
+                    "transfer failed ({:?}), retrying in {}s ({}/{})",
+                    e,
+                    delay.as_secs(),
+                    attempt,
+                    options.retries
+                );
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(e) => break Err(e),
+        }
+    };
 
-    do_copy(Location::Remote(url), Location::Local(local_path.clone())).unwrap();
+    match result {
+        Ok(outcome) if outcome.skipped => {
+            println!("{} is up to date, skipping", destination);
+            Ok(())
+        }
+        Ok(outcome) => {
+            if let Some(path) = &local_destination_path {
+                // The transfer completed and the `.tmp` file was already
+                // renamed into place, so there's no resume to carry forward:
+                // clear the sidecar instead of persisting the validator we
+                // just received, or it would linger forever next to a
+                // finished download.
+                update_resume_sidecar(path, None);
+                if let Some(cache_entry) = &outcome.cache_entry {
+                    cache_entry.save(path);
+                }
+            }
+            Ok(())
+        }
+        Err(e) => {
+            if let Some(path) = &local_destination_path {
+                let _ = std::fs::remove_file(tmp_path_for(path));
+                let _ = std::fs::remove_file(resume_sidecar_path(path));
+                let _ = std::fs::remove_file(segmented_tmp_path_for(path));
+            }
+            Err(e)
+        }
+    }
+}
 
-    let contents = std::fs::read_to_string(local_path).unwrap();
-    assert_eq!(contents, "file_data");
+/// Appends each `/`-separated component of `relative` to `location` in turn.
+fn push_relative(location: &mut Location, relative: &str) {
+    for component in relative.split('/') {
+        location.push(component);
+    }
+}
+
+/// Whether a listing/manifest entry is safe to descend into: not an absolute path, and
+/// none of its `/`-separated components are `..`, since `push_relative` would otherwise
+/// walk outside the destination directory.
+fn is_safe_listing_entry(name: &str) -> bool {
+    !name.starts_with('/') && !name.split('/').any(|component| component == "..")
+}
+
+/// Parses the child entries out of a JSON manifest: a flat array of filenames, with a
+/// trailing `/` marking a subdirectory.
+fn parse_json_manifest(body: &str) -> Vec<(String, bool)> {
+    body.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|s| s.trim().trim_matches('"'))
+        .filter(|s| !s.is_empty())
+        .filter(|s| is_safe_listing_entry(s))
+        .map(|name| (name.trim_end_matches('/').to_string(), name.ends_with('/')))
+        .collect()
+}
+
+/// Parses the child entries out of an autoindex-style HTML directory listing by
+/// scraping anchor `href` targets, skipping parent-directory, query, and off-host links.
+fn parse_html_listing(body: &str) -> Vec<(String, bool)> {
+    let mut entries = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("href=\"") {
+        rest = &rest[start + "href=\"".len()..];
+        let end = match rest.find('"') {
+            Some(end) => end,
+            None => break,
+        };
+        let href = &rest[..end];
+        rest = &rest[end..];
+        if href.is_empty()
+            || href.starts_with('?')
+            || href.starts_with('#')
+            || href.contains("://")
+            || !is_safe_listing_entry(href)
+        {
+            continue;
+        }
+        entries.push((href.trim_end_matches('/').to_string(), href.ends_with('/')));
+    }
+    entries
+}
+
+/// Lists the immediate children of a directory `Location`, each tagged with whether
+/// it is itself a subdirectory.
+fn list_dir_children(
+    dir: &Location,
+    http_client: &mut Transport,
+    request_config: &RequestConfig,
+) -> Result<Vec<(String, bool)>> {
+    match dir {
+        Location::Local(path) => {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                let is_dir = entry.file_type()?.is_dir();
+                entries.push((entry.file_name().to_string_lossy().into_owned(), is_dir));
+            }
+            Ok(entries)
+        }
+        Location::Remote(url) => {
+            let mut request = http_client.get(url.clone())?;
+            request.set_header("User-Agent", USER_AGENT);
+            request_config.apply(&mut request);
+            let mut response = request.finish()?;
+            let content_type = response
+                .headers
+                .get("Content-Type")
+                .unwrap_or("")
+                .to_string();
+            let mut body = String::new();
+            io::Read::read_to_string(&mut response.body, &mut body)?;
+            Ok(if content_type.contains("json") {
+                parse_json_manifest(&body)
+            } else {
+                parse_html_listing(&body)
+            })
+        }
+    }
+}
+
+/// Caps how many directory levels `list_files_recursive` will descend, so a remote
+/// tree that lists itself (directly or through an alias) can't recurse forever.
+const MAX_RECURSIVE_DEPTH: usize = 64;
+
+/// Recursively enumerates every file under the directory `root`, returning paths
+/// relative to `root` with `/` separating components.
+fn list_files_recursive(
+    root: &Location,
+    http_client: &mut Transport,
+    request_config: &RequestConfig,
+) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(relative) = pending.pop() {
+        if relative.matches('/').count() >= MAX_RECURSIVE_DEPTH {
+            return Err(Error::RecursionTooDeep);
+        }
+        let mut dir = root.clone();
+        if !relative.is_empty() {
+            push_relative(&mut dir, &relative);
+        }
+        for (name, is_dir) in list_dir_children(&dir, http_client, request_config)? {
+            let child = if relative.is_empty() {
+                name
+            } else {
+                format!("{}/{}", relative, name)
+            };
+            if is_dir {
+                pending.push(child);
+            } else {
+                files.push(child);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Mirrors a directory tree, copying every file `source` contains to the matching
+/// path under `destination`, recreating the subtree as it goes.
+fn do_copy_recursive(options: &Options, source: Location, destination: Location) -> Result<()> {
+    let mut http_client = Transport::new();
+    let request_config = RequestConfig::new(options);
+    let files = list_files_recursive(&source, &mut http_client, &request_config)?;
+    let total_files = files.len();
+    let mut bytes_done = 0u64;
+
+    for (index, relative) in files.iter().enumerate() {
+        let mut entry_source = source.clone();
+        push_relative(&mut entry_source, relative);
+        let mut entry_destination = destination.clone();
+        push_relative(&mut entry_destination, relative);
+
+        if let Location::Local(path) = &entry_destination {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+
+        do_copy(options, entry_source, entry_destination.clone())?;
+
+        if let Location::Local(path) = &entry_destination {
+            bytes_done += std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        }
+        println!(
+            "[{}/{}] files copied, {} bytes total",
+            index + 1,
+            total_files,
+            bytes_done
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+use http_io::protocol::HttpResponse;
+#[cfg(test)]
+use http_io::server::{HttpRequestHandler, HttpServer};
+
+#[cfg(test)]
+fn test_options(source: Location, destination: Location) -> Options {
+    Options {
+        source,
+        destination,
+        max_redirects: 10,
+        resume: false,
+        retries: 0,
+        compress: false,
+        connections: 1,
+        recursive: false,
+        cache: false,
+        headers: Vec::new(),
+        user: None,
+    }
+}
+
+#[cfg(test)]
+struct TestDownloadHandler(String);
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for TestDownloadHandler {
+    type Error = http_io::error::Error;
+
+    fn get(&mut self, _uri: String) -> http_io::error::Result<HttpResponse<Box<dyn io::Read>>> {
+        Ok(HttpResponse::from_string(HttpStatus::OK, &self.0))
+    }
+}
+
+/// End-to-end integration test of downloading a file from an HTTP server.
+#[test]
+fn test_download() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = TestDownloadHandler("file_data".into());
+    let mut server = HttpServer::new(server_socket, handler);
+    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+
+    let options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    let contents = std::fs::read_to_string(local_path).unwrap();
+    assert_eq!(contents, "file_data");
 
     server_handle.join().unwrap();
 }
@@ -389,12 +1482,616 @@ fn test_upload() {
     write!(&mut temporary_file, "file_data").unwrap();
     let local_path = temporary_file.path().to_path_buf();
 
-    do_copy(Location::Local(local_path.clone()), Location::Remote(url)).unwrap();
+    let options = test_options(Location::Local(local_path.clone()), Location::Remote(url));
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
 
     server_handle.join().unwrap();
 }
 
+#[cfg(test)]
+struct TestResumeHandler(String);
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for TestResumeHandler {
+    type Error = http_io::error::Error;
+
+    fn get(&mut self, _uri: String) -> http_io::error::Result<HttpResponse<Box<dyn io::Read>>> {
+        let mut response = HttpResponse::from_string(HttpStatus::PartialContent, self.0.clone());
+        response.add_header("Content-Range", "bytes 5-8/9");
+        response.add_header("ETag", "\"resumed\"");
+        Ok(response)
+    }
+}
+
+/// A `--continue`d download should append to the bytes already staged.
+#[test]
+fn test_resume_appends_to_partial_download() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = TestResumeHandler("data".into());
+    let mut server = HttpServer::new(server_socket, handler);
+    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+    std::fs::write(tmp_path_for(&local_path), "file_").unwrap();
+
+    let mut options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    options.resume = true;
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    let contents = std::fs::read_to_string(&local_path).unwrap();
+    assert_eq!(contents, "file_data");
+    assert!(!tmp_path_for(&local_path).exists());
+
+    server_handle.join().unwrap();
+}
+
+/// A `--continue`d request must not advertise `Accept-Encoding`, since the bytes
+/// already staged are a plaintext continuation that a compressed retry can't resume.
+#[test]
+fn test_resume_omits_accept_encoding() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+
+    let server_handle = std::thread::spawn(move || {
+        let (mut stream, _) = server_socket.accept().unwrap();
+        let mut reader = io::BufReader::new(&mut stream);
+        let mut headers = String::new();
+        loop {
+            let mut line = String::new();
+            std::io::BufRead::read_line(&mut reader, &mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            headers.push_str(&line);
+        }
+        use std::io::Write;
+        write!(
+            stream,
+            "HTTP/1.1 206 Partial Content\r\n\
+             Content-Range: bytes 5-8/9\r\n\
+             ETag: \"resumed\"\r\n\
+             Content-Length: 4\r\n\r\ndata"
+        )
+        .unwrap();
+        headers
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+    std::fs::write(tmp_path_for(&local_path), "file_").unwrap();
+
+    let mut options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    options.resume = true;
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    let headers = server_handle.join().unwrap();
+    assert!(!headers.to_lowercase().contains("accept-encoding"));
+    assert!(headers.contains("Range: bytes=5-"));
+}
+
+/// A download that exhausts its retries should not leave a `.tmp`/`.resume` file behind.
+#[test]
+fn test_exhausted_retries_removes_temp_file() {
+    // Bind then immediately drop the listener so the port is refused rather
+    // than connected to, giving a deterministic, instant connection failure.
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    drop(server_socket);
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+    std::fs::write(tmp_path_for(&local_path), "stale_partial_data").unwrap();
+    std::fs::write(resume_sidecar_path(&local_path), "\"stale-etag\"").unwrap();
+
+    let mut options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    options.resume = true;
+    options.retries = 0;
+    let result = do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    );
+
+    assert!(result.is_err());
+    assert!(!tmp_path_for(&local_path).exists());
+    assert!(!resume_sidecar_path(&local_path).exists());
+}
+
+#[cfg(test)]
+struct TestGzipHandler(Vec<u8>);
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for TestGzipHandler {
+    type Error = http_io::error::Error;
+
+    fn get(&mut self, _uri: String) -> http_io::error::Result<HttpResponse<Box<dyn io::Read>>> {
+        let body: Box<dyn io::Read> = Box::new(io::Cursor::new(self.0.clone()));
+        let mut response = HttpResponse::new(HttpStatus::OK, body);
+        response.add_header("Content-Encoding", "gzip");
+        Ok(response)
+    }
+}
+
+/// A response carrying `Content-Encoding: gzip` should land on disk already decompressed.
+#[test]
+fn test_download_decompresses_gzip_content_encoding() {
+    use std::io::Write;
+
+    let mut compressed = Vec::new();
+    {
+        let mut encoder = GzEncoder::new(&mut compressed, Compression::default());
+        encoder.write_all(b"file_data").unwrap();
+        encoder.finish().unwrap();
+    }
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = TestGzipHandler(compressed);
+    let mut server = HttpServer::new(server_socket, handler);
+    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+
+    let options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    let contents = std::fs::read_to_string(&local_path).unwrap();
+    assert_eq!(contents, "file_data");
+
+    server_handle.join().unwrap();
+}
+
+/// `download_segment` must reject a non-`206` response instead of writing it into its range.
+#[test]
+fn test_download_segment_rejects_non_206_response() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = TestDownloadHandler("not a range".into());
+    let mut server = HttpServer::new(server_socket, handler);
+    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    File::create(temporary_file.path())
+        .unwrap()
+        .set_len(8)
+        .unwrap();
+
+    let result = download_segment(
+        url,
+        temporary_file.path().to_path_buf(),
+        0,
+        7,
+        10,
+        RequestConfig::default(),
+        Arc::new(AtomicU64::new(0)),
+        ProgressBar::hidden(),
+    );
+
+    assert!(matches!(
+        result,
+        Err(Error::UnexpectedStatus(HttpStatus::OK))
+    ));
+
+    server_handle.join().unwrap();
+}
+
+/// `download_segment` should write the response body at the requested byte offset.
+#[test]
+fn test_download_segment_writes_range_into_file() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = TestResumeHandler("segment".into());
+    let mut server = HttpServer::new(server_socket, handler);
+    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    File::create(temporary_file.path())
+        .unwrap()
+        .set_len(20)
+        .unwrap();
+
+    download_segment(
+        url,
+        temporary_file.path().to_path_buf(),
+        5,
+        11,
+        10,
+        RequestConfig::default(),
+        Arc::new(AtomicU64::new(0)),
+        ProgressBar::hidden(),
+    )
+    .unwrap();
+
+    let contents = std::fs::read(temporary_file.path()).unwrap();
+    assert_eq!(&contents[5..12], b"segment");
+
+    server_handle.join().unwrap();
+}
+
+/// `segment_ranges` should fold any remainder from an uneven split into the last segment.
+#[test]
+fn test_segment_ranges_splits_with_remainder_in_last_segment() {
+    assert_eq!(segment_ranges(10, 3), vec![(0, 2), (3, 5), (6, 9)]);
+    assert_eq!(segment_ranges(10, 1), vec![(0, 9)]);
+}
+
+/// `clamp_connections` must never exceed `total_len`, or `segment_ranges` would underflow.
+#[test]
+fn test_clamp_connections_bounds_to_total_len() {
+    assert_eq!(clamp_connections(99_999_999, 20_000_000), 20_000_000);
+    assert_eq!(clamp_connections(4, 20_000_000), 4);
+    assert_eq!(clamp_connections(0, 20_000_000), 1);
+}
+
+/// `--recursive` over a local tree should mirror every file, recreating the subdirectories.
+#[test]
+fn test_recursive_copies_nested_local_tree() {
+    let source_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(source_dir.path().join("sub")).unwrap();
+    std::fs::write(source_dir.path().join("a.txt"), "a").unwrap();
+    std::fs::write(source_dir.path().join("sub/b.txt"), "b").unwrap();
+
+    let destination_dir = tempfile::tempdir().unwrap();
+    let destination_path = destination_dir.path().join("out");
+
+    let mut options = test_options(
+        Location::Local(source_dir.path().to_path_buf()),
+        Location::Local(destination_path.clone()),
+    );
+    options.recursive = true;
+    do_copy_recursive(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(destination_path.join("a.txt")).unwrap(),
+        "a"
+    );
+    assert_eq!(
+        std::fs::read_to_string(destination_path.join("sub/b.txt")).unwrap(),
+        "b"
+    );
+}
+
+#[cfg(test)]
+struct TestRecursiveListingHandler;
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for TestRecursiveListingHandler {
+    type Error = http_io::error::Error;
+
+    fn get<'a>(
+        &'a mut self,
+        uri: String,
+    ) -> http_io::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        Ok(match uri.as_str() {
+            "/" => HttpResponse::from_string(
+                HttpStatus::OK,
+                concat!(
+                    "<html><body>",
+                    "<a href=\"a.txt\">a.txt</a>",
+                    "<a href=\"b.txt\">b.txt</a>",
+                    "<a href=\"../up\">up</a>",
+                    "<a href=\"/root\">root</a>",
+                    "<a href=\"?query\">query</a>",
+                    "</body></html>",
+                )
+                .to_string(),
+            ),
+            "/a.txt" => HttpResponse::from_string(HttpStatus::OK, "a".to_string()),
+            "/b.txt" => HttpResponse::from_string(HttpStatus::OK, "b".to_string()),
+            _ => HttpResponse::from_string(HttpStatus::NotFound, "not found".to_string()),
+        })
+    }
+}
+
+/// `--recursive` over a remote directory should scrape the autoindex-style HTML
+/// listing, skip the links that don't name an actual child, and fetch each real child.
+#[test]
+fn test_recursive_copies_remote_html_listing() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, TestRecursiveListingHandler);
+    let server_handle = std::thread::spawn(move || {
+        for _ in 0..3 {
+            server.serve_one().unwrap();
+        }
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let destination_dir = tempfile::tempdir().unwrap();
+    let destination_path = destination_dir.path().join("out");
+
+    let mut options = test_options(
+        Location::Remote(url),
+        Location::Local(destination_path.clone()),
+    );
+    options.recursive = true;
+    do_copy_recursive(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(destination_path.join("a.txt")).unwrap(),
+        "a"
+    );
+    assert_eq!(
+        std::fs::read_to_string(destination_path.join("b.txt")).unwrap(),
+        "b"
+    );
+
+    server_handle.join().unwrap();
+}
+
+#[cfg(test)]
+struct TestLoopingListingHandler;
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for TestLoopingListingHandler {
+    type Error = http_io::error::Error;
+
+    fn get<'a>(
+        &'a mut self,
+        _uri: String,
+    ) -> http_io::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        Ok(HttpResponse::from_string(
+            HttpStatus::OK,
+            "<a href=\"loop/\">loop</a>".to_string(),
+        ))
+    }
+}
+
+/// A remote directory that lists itself at every level must not make
+/// `list_files_recursive` recurse forever.
+#[test]
+fn test_recursive_listing_cycle_is_bounded() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, TestLoopingListingHandler);
+    let server_handle = std::thread::spawn(move || {
+        for _ in 0..=MAX_RECURSIVE_DEPTH {
+            server.serve_one().unwrap();
+        }
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let mut http_client = Transport::new();
+    let result = list_files_recursive(
+        &Location::Remote(url),
+        &mut http_client,
+        &RequestConfig::default(),
+    );
+
+    assert!(matches!(result, Err(Error::RecursionTooDeep)));
+
+    server_handle.join().unwrap();
+}
+
+/// `parse_json_manifest` should pick files and directories out of a flat JSON array,
+/// using a trailing `/` to mark a directory.
+#[test]
+fn parse_json_manifest_splits_files_and_directories() {
+    let entries = parse_json_manifest(r#"["a.txt", "sub/"]"#);
+    assert_eq!(
+        entries,
+        vec![("a.txt".to_string(), false), ("sub".to_string(), true)]
+    );
+}
+
+/// `parse_json_manifest` must reject any entry that could escape the destination
+/// directory via `push_relative`, whether it's an absolute path or an embedded `..`.
+#[test]
+fn parse_json_manifest_rejects_path_traversal() {
+    let entries = parse_json_manifest(
+        r#"["../../escape.txt", "/etc/passwd", "sub/../../../etc/passwd", "ok.txt"]"#,
+    );
+    assert_eq!(entries, vec![("ok.txt".to_string(), false)]);
+}
+
+/// `parse_html_listing` should pick out real relative child links while skipping
+/// parent/absolute/query links that don't name a child of the current directory.
+#[test]
+fn parse_html_listing_skips_non_child_links() {
+    let body = concat!(
+        "<a href=\"a.txt\">a.txt</a>",
+        "<a href=\"sub/\">sub</a>",
+        "<a href=\"../up\">up</a>",
+        "<a href=\"/root\">root</a>",
+        "<a href=\"?query\">query</a>",
+        "<a href=\"sub/../../../etc/passwd\">escape</a>",
+    );
+    let entries = parse_html_listing(body);
+    assert_eq!(
+        entries,
+        vec![("a.txt".to_string(), false), ("sub".to_string(), true)]
+    );
+}
+
+#[cfg(test)]
+struct TestCacheHandler;
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for TestCacheHandler {
+    type Error = http_io::error::Error;
+
+    fn get(&mut self, _uri: String) -> http_io::error::Result<HttpResponse<Box<dyn io::Read>>> {
+        Ok(HttpResponse::from_string(HttpStatus::NotModified, ""))
+    }
+}
+
+/// A `--cache`d copy whose source replies `304 Not Modified` should skip the transfer.
+#[test]
+fn test_cache_skips_unchanged_download() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = TestCacheHandler;
+    let mut server = HttpServer::new(server_socket, handler);
+    let server_handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(temporary_file.path(), "stale_data").unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+
+    let mut options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    options.cache = true;
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    let contents = std::fs::read_to_string(&local_path).unwrap();
+    assert_eq!(contents, "stale_data");
+
+    server_handle.join().unwrap();
+}
+
+/// A `--cache`d copy must send its recorded `ETag` as `If-None-Match` and save the
+/// validator a `200` response comes back with.
+#[test]
+fn test_cache_sends_validator_and_saves_new_one() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+
+    let server_handle = std::thread::spawn(move || {
+        let (mut stream, _) = server_socket.accept().unwrap();
+        let mut reader = io::BufReader::new(&mut stream);
+        let mut headers = String::new();
+        loop {
+            let mut line = String::new();
+            std::io::BufRead::read_line(&mut reader, &mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            headers.push_str(&line);
+        }
+        use std::io::Write;
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\n\
+             ETag: \"new\"\r\n\
+             Content-Length: 8\r\n\r\nnew_data"
+        )
+        .unwrap();
+        headers
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port())
+        .parse()
+        .unwrap();
+    let temporary_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(temporary_file.path(), "stale_data").unwrap();
+    let local_path = temporary_file.path().to_path_buf();
+    CacheEntry {
+        etag: Some("\"stale\"".into()),
+        last_modified: None,
+        length: None,
+    }
+    .save(&local_path);
+
+    let mut options = test_options(Location::Remote(url), Location::Local(local_path.clone()));
+    options.cache = true;
+    do_copy(
+        &options,
+        options.source.clone(),
+        options.destination.clone(),
+    )
+    .unwrap();
+
+    let headers = server_handle.join().unwrap();
+    assert!(headers.contains("If-None-Match: \"stale\""));
+
+    let contents = std::fs::read_to_string(&local_path).unwrap();
+    assert_eq!(contents, "new_data");
+
+    let saved = CacheEntry::load(&local_path).unwrap();
+    assert_eq!(saved.etag.as_deref(), Some("\"new\""));
+}
+
+#[test]
+fn request_config_builds_basic_auth_header() {
+    let mut options = test_options(Location::Local("/a".into()), Location::Local("/b".into()));
+    options.user = Some(("alice".to_string(), "secret".to_string()));
+
+    let config = RequestConfig::new(&options);
+
+    assert_eq!(
+        config.authorization.as_deref(),
+        Some("Basic YWxpY2U6c2VjcmV0")
+    );
+}
+
+#[test]
+fn is_https_detects_scheme() {
+    let https_url: Url = "https://ex.com/a".parse().unwrap();
+    let http_url: Url = "http://ex.com/a".parse().unwrap();
+
+    assert!(is_https(&https_url));
+    assert!(!is_https(&http_url));
+}
+
 fn main() -> Result<()> {
     let options = Options::from_args();
-    do_copy(options.source, options.destination)
+    let source = options.source.clone();
+    let destination = options.destination.clone();
+    if options.recursive && source.is_dir() {
+        do_copy_recursive(&options, source, destination)
+    } else {
+        do_copy(&options, source, destination)
+    }
 }